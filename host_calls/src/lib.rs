@@ -4,6 +4,9 @@ mod remote_attestation;
 #[cfg(feature = "std")]
 use remote_attestation::verify_mra_cert;
 
+#[cfg(feature = "std")]
+pub use remote_attestation::verify_known_good_attestation;
+
 use codec::{Decode, Encode};
 use runtime_interface::runtime_interface;
 
@@ -28,7 +28,7 @@ use sgx_types::*;
 use sgx_ucrypto::SgxEccHandle;
 
 use super::{SgxReport, SgxStatus};
-use codec::Encode;
+use codec::{Decode, Encode};
 
 type SignatureAlgorithms = &'static [&'static webpki::SignatureAlgorithm];
 
@@ -48,6 +48,14 @@ static SUPPORTED_SIG_ALGS: SignatureAlgorithms = &[
 
 pub const IAS_REPORT_CA: &[u8] = include_bytes!("../AttestationReportSigningCACert.pem");
 
+// known-good attestation used to check that the verifier works on this host
+// reproduce with "substratee_worker dump_ra" and "substratee_worker getsignkey"
+const KNOWN_GOOD_CERT: &[u8] = include_bytes!("../test/test_ra_cert_MRSIGNER1_MRENCLAVE1.der");
+const KNOWN_GOOD_SIGNER_ATTN: &[u8] =
+    include_bytes!("../test/test_ra_signer_attn_MRSIGNER1_MRENCLAVE1.bin");
+const KNOWN_GOOD_SIGNER_PUB: &[u8] =
+    include_bytes!("../test/test_ra_signer_pubkey_MRSIGNER1_MRENCLAVE1.bin");
+
 // prevents panics in case of index out of bounds
 fn safe_indexing(data: &[u8], start: usize, end: usize) -> Result<&[u8], &'static str> {
     if start > end {
@@ -65,6 +73,16 @@ fn safe_indexing_one(data: &[u8], idx: usize) -> Result<u8, &'static str> {
     Ok(data[idx])
 }
 
+/// Verifies a known-good attestation. A misconfigured verifier (e.g. a broken CA
+/// certificate) would reject every registration, so this can be used as a health check.
+pub fn verify_known_good_attestation() -> bool {
+    let signer_attn: [u32; 16] = match Decode::decode(&mut &KNOWN_GOOD_SIGNER_ATTN[..]) {
+        Ok(attn) => attn,
+        Err(_) => return false,
+    };
+    verify_mra_cert(KNOWN_GOOD_CERT, &signer_attn, KNOWN_GOOD_SIGNER_PUB).is_ok()
+}
+
 // make sure this function doesn't panic!
 pub fn verify_mra_cert(
     cert_der: &[u8],
@@ -329,7 +347,6 @@ fn verify_attn_report(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use codec::Decode;
     // reproduce with "substratee_worker dump_ra"
     const TEST1_CERT: &[u8] = include_bytes!("../test/test_ra_cert_MRSIGNER1_MRENCLAVE1.der");
     const TEST2_CERT: &[u8] = include_bytes!("../test/test_ra_cert_MRSIGNER2_MRENCLAVE2.der");
@@ -387,6 +404,20 @@ mod tests {
         assert_eq!(report.status, SgxStatus::GroupOutOfDate);
     }
 
+    #[test]
+    fn verify_mra_cert_returns_ias_timestamp() {
+        let signer_attn: [u32; 16] = Decode::decode(&mut TEST1_SIGNER_ATTN).unwrap();
+        let report = verify_mra_cert(TEST1_CERT, &signer_attn, TEST1_SIGNER_PUB).unwrap();
+        let report: SgxReport = Decode::decode(&mut &report[..]).unwrap();
+        // "timestamp" of the IAS report in TEST1_CERT as unix epoch
+        assert_eq!(report.timestamp, TEST_TIMESTAMP);
+    }
+
+    #[test]
+    fn verify_known_good_attestation_works() {
+        assert!(verify_known_good_attestation());
+    }
+
     #[test]
     fn verify_mra_cert_wrong_signer_should_fail() {
         // wrong ed25519, good ephemeral ecdsa
@@ -97,7 +97,7 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
     spec_name: create_runtime_str!("node"),
     impl_name: create_runtime_str!("test-node"),
     authoring_version: 1,
-    spec_version: 1,
+    spec_version: 2,
     impl_version: 1,
     apis: RUNTIME_API_VERSIONS,
 };
@@ -234,8 +234,34 @@ impl sudo::Trait for Runtime {
 }
 
 // --- start changed by SCS -----------------------------------------------
+parameter_types! {
+    pub const MinimumEnclaveBalance: Balance = 0;
+    // Intel recommends 90 days, but we prefer enclaves to re-attest daily.
+    pub const MaxEnclaveAge: u64 = 24 * 60 * 60;
+    pub const PurgeStaleEnclaves: bool = false;
+    pub const MaxPurgeScan: u32 = 8;
+    pub const MaxRaReportLen: u32 = 4096;
+    pub const MaxUrlLen: u32 = 256;
+    pub const MaxConfirmationsPerBatch: u32 = 64;
+    pub const IpfsHistoryDepth: u64 = 100;
+    pub const AcceptGroupOutOfDate: bool = false;
+    pub const RequireFreshAttestation: bool = false;
+}
+
 impl substratee_registry::Trait for Runtime {
     type Event = Event;
+    type MinimumEnclaveBalance = MinimumEnclaveBalance;
+    type MaxEnclaveAge = MaxEnclaveAge;
+    type PurgeStaleEnclaves = PurgeStaleEnclaves;
+    type MaxPurgeScan = MaxPurgeScan;
+    type MaxRaReportLen = MaxRaReportLen;
+    type MaxUrlLen = MaxUrlLen;
+    type MaxConfirmationsPerBatch = MaxConfirmationsPerBatch;
+    type IpfsHistoryDepth = IpfsHistoryDepth;
+    type AcceptGroupOutOfDate = AcceptGroupOutOfDate;
+    type RequireFreshAttestation = RequireFreshAttestation;
+    type Signature = Signature;
+    type EnclaveSigner = <Signature as Verify>::Signer;
 }
 // --- end changed by SCS -------------------------------------------------
 
@@ -254,7 +280,7 @@ construct_runtime!(
 		TransactionPayment: transaction_payment::{Module, Storage},
 		Sudo: sudo,
 		// --- start changed by SCS -----------------------------------------------
-		SubstraTEERegistry: substratee_registry::{Module, Call, Storage, Event<T>},
+		SubstraTEERegistry: substratee_registry::{Module, Call, Storage, Event<T>, Config<T>},
 		// --- end changed by SCS -------------------------------------------------
 		RandomnessCollectiveFlip: randomness_collective_flip::{Module, Call, Storage},
 	}
@@ -302,6 +328,12 @@ impl_runtime_apis! {
         }
     }
 
+    impl substratee_registry::SubstraTEERegistryApi<Block, AccountId> for Runtime {
+        fn enclaves() -> Vec<(u64, substratee_registry::Enclave<AccountId, Vec<u8>>)> {
+            SubstraTEERegistry::enclaves()
+        }
+    }
+
     impl sr_api::Metadata<Block> for Runtime {
         fn metadata() -> OpaqueMetadata {
             Runtime::metadata().into()
@@ -15,24 +15,58 @@
 
 */
 
-use codec::{Decode, Encode};
+use codec::{Codec, Decode, Encode, EncodeLike, Input, Output};
 use host_calls::runtime_interfaces::verify_ra_report;
-use host_calls::SgxReport;
+use host_calls::{SgxReport, SgxStatus};
 use primitives::H256;
 use rstd::prelude::*;
 use rstd::str;
 use runtime_io::misc::print_utf8;
-use support::{decl_event, decl_module, decl_storage, dispatch::Result, ensure, StorageLinkedMap};
-use system::ensure_signed;
-
-pub trait Trait: balances::Trait {
+use sr_primitives::traits::{Hash as HashT, IdentifyAccount, SaturatedConversion, Verify};
+use sr_primitives::weights::{
+    ClassifyDispatch, DispatchClass, PaysFee, SimpleDispatchInfo, WeighData, Weight,
+};
+use support::{
+    decl_error, decl_event, decl_module, decl_storage, dispatch::Result, ensure, traits::Get,
+    Parameter, StorageLinkedMap,
+};
+use system::{ensure_root, ensure_signed};
+
+pub trait Trait: balances::Trait + timestamp::Trait {
     type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+    /// The minimum free balance an enclave account must hold in order to register.
+    /// Enclaves need funds to pay the fees for `confirm_call`.
+    type MinimumEnclaveBalance: Get<Self::Balance>;
+    /// Seconds after its remote attestation after which an enclave is considered stale.
+    type MaxEnclaveAge: Get<u64>;
+    /// If set, every successful registration removes the oldest stale enclave (if any).
+    type PurgeStaleEnclaves: Get<bool>;
+    /// The number of enclaves a registration inspects when looking for a stale one to purge.
+    type MaxPurgeScan: Get<u32>;
+    /// The maximum length of an RA report in bytes.
+    type MaxRaReportLen: Get<u32>;
+    /// The maximum length of a worker URL in bytes.
+    type MaxUrlLen: Get<u32>;
+    /// The maximum number of calls that can be confirmed with one `confirm_calls`.
+    type MaxConfirmationsPerBatch: Get<u32>;
+    /// The number of most recent IPFS hashes kept in `IpfsHashHistory` per shard.
+    type IpfsHistoryDepth: Get<u64>;
+    /// If set, enclaves on platforms with an out of date TCB (GROUP_OUT_OF_DATE) are
+    /// accepted as well. Meant for development setups only.
+    type AcceptGroupOutOfDate: Get<bool>;
+    /// If set, only enclaves attested within `MaxEnclaveAge` may confirm calls.
+    type RequireFreshAttestation: Get<bool>;
+    /// The signature enclaves sign their confirmations with.
+    type Signature: Parameter + Verify<Signer = Self::EnclaveSigner>;
+    /// The public key behind `Signature`. It identifies the enclave's account.
+    type EnclaveSigner: IdentifyAccount<AccountId = Self::AccountId>;
 }
 
-const MAX_RA_REPORT_LEN: usize = 4096;
-const MAX_URL_LEN: usize = 256;
+/// Encoded enclaves are prefixed with this version, so that a future layout can be told
+/// apart from the current one when migrating storage.
+pub const ENCLAVE_VERSION: u8 = 1;
 
-#[derive(Encode, Decode, Default, Copy, Clone, PartialEq)]
+#[derive(Default, Copy, Clone, PartialEq)]
 #[cfg_attr(feature = "std", derive(Debug))]
 pub struct Enclave<PubKey, Url> {
     pub pubkey: PubKey, // FIXME: this is redundant information
@@ -41,8 +75,84 @@ pub struct Enclave<PubKey, Url> {
     pub url: Url,       // utf8 encoded url
 }
 
+impl<PubKey: Encode, Url: Encode> Encode for Enclave<PubKey, Url> {
+    fn encode_to<O: Output>(&self, dest: &mut O) {
+        ENCLAVE_VERSION.encode_to(dest);
+        self.pubkey.encode_to(dest);
+        self.mr_enclave.encode_to(dest);
+        self.timestamp.encode_to(dest);
+        self.url.encode_to(dest);
+    }
+}
+
+impl<PubKey: Encode, Url: Encode> EncodeLike for Enclave<PubKey, Url> {}
+
+impl<PubKey: Decode, Url: Decode> Decode for Enclave<PubKey, Url> {
+    fn decode<I: Input>(input: &mut I) -> rstd::result::Result<Self, codec::Error> {
+        match u8::decode(input)? {
+            ENCLAVE_VERSION => Ok(Enclave {
+                pubkey: Decode::decode(input)?,
+                mr_enclave: Decode::decode(input)?,
+                timestamp: Decode::decode(input)?,
+                url: Decode::decode(input)?,
+            }),
+            _ => Err("unknown Enclave encoding version".into()),
+        }
+    }
+}
+
+/// The layout enclaves were stored with before `ENCLAVE_VERSION` was introduced. Only
+/// needed to migrate `EnclaveRegistry`.
+#[derive(Encode, Decode, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct LegacyEnclave<PubKey, Url> {
+    pub pubkey: PubKey,
+    pub mr_enclave: [u8; 32],
+    pub timestamp: i64,
+    pub url: Url,
+}
+
+impl<PubKey, Url> From<LegacyEnclave<PubKey, Url>> for Enclave<PubKey, Url> {
+    fn from(legacy: LegacyEnclave<PubKey, Url>) -> Self {
+        Enclave {
+            pubkey: legacy.pubkey,
+            mr_enclave: legacy.mr_enclave,
+            timestamp: legacy.timestamp,
+            url: legacy.url,
+        }
+    }
+}
+
 pub type ShardIdentifier = H256;
 
+/// A processed call: the shard, the call hash and the IPFS hash of the resulting state.
+/// Enclaves sign the SCALE encoding of it when confirming.
+pub type Confirmation = (ShardIdentifier, Vec<u8>, Vec<u8>);
+
+/// Weight of confirming a single call: one signature check and a handful of storage writes.
+pub const CONFIRMATION_WEIGHT: Weight = 10_000;
+
+/// Weighs `confirm_calls` by the number of confirmations in the batch.
+pub struct ConfirmCallsWeight;
+
+impl<T> WeighData<(&Vec<T>,)> for ConfirmCallsWeight {
+    fn weigh_data(&self, (confirmations,): (&Vec<T>,)) -> Weight {
+        CONFIRMATION_WEIGHT.saturating_mul(confirmations.len().max(1) as Weight)
+    }
+}
+
+impl<T> ClassifyDispatch<T> for ConfirmCallsWeight {
+    fn classify_dispatch(&self, _: T) -> DispatchClass {
+        DispatchClass::Normal
+    }
+}
+
+impl PaysFee for ConfirmCallsWeight {
+    fn pays_fee(&self) -> bool {
+        true
+    }
+}
+
 #[derive(Encode, Decode, Debug, Default, Clone, PartialEq, Eq)]
 //#[cfg_attr(feature = "std", derive(Debug))]
 pub struct Request {
@@ -50,6 +160,14 @@ pub struct Request {
     pub cyphertext: Vec<u8>,
 }
 
+sr_api::decl_runtime_apis! {
+    /// Reads the whole enclave registry in one call.
+    pub trait SubstraTEERegistryApi<AccountId> where AccountId: Codec {
+        /// All registered enclaves together with their index, ordered by index.
+        fn enclaves() -> Vec<(u64, Enclave<AccountId, Vec<u8>>)>;
+    }
+}
+
 decl_event!(
 	pub enum Event<T>
 	where
@@ -60,9 +178,40 @@ decl_event!(
 		UpdatedIpfsHash(ShardIdentifier, u64, Vec<u8>),
 		Forwarded(Request),
 		CallConfirmed(AccountId, Vec<u8>),
+		ShardOrphaned(ShardIdentifier),
+		ProcessedCallsUpdated(AccountId, u64),
+		IpfsHistoryPruned(ShardIdentifier, u64),
+		AllowedMrEnclaveChanged(Option<[u8; 32]>),
 	}
 );
 
+decl_error! {
+    pub enum Error {
+        /// The enclave account's free balance is below `MinimumEnclaveBalance`
+        InsufficientEnclaveBalance,
+        /// The remote attestation report is empty
+        EmptyReport,
+        /// One of the ECDSA signature components in `ra_signer_attn` is zero
+        InvalidSignerAttestation,
+        /// More confirmations than `MaxConfirmationsPerBatch` were submitted at once
+        TooManyConfirmations,
+        /// `confirm_calls` was submitted without any confirmation
+        NoConfirmations,
+        /// The quote status in the RA report is not acceptable
+        InsufficientQuoteStatus,
+        /// The MRENCLAVE in the RA report doesn't match `AllowedMrEnclave`
+        MrEnclaveNotAllowed,
+        /// The enclave has been attested within `MaxEnclaveAge`
+        EnclaveNotStale,
+        /// The worker URL isn't UTF-8 or has no ws(s):// or http(s):// scheme
+        InvalidUrl,
+        /// The enclave's attestation is older than `MaxEnclaveAge`
+        StaleEnclave,
+        /// The confirmation isn't signed by the enclave's registered key
+        InvalidConfirmationSignature,
+    }
+}
+
 decl_storage! {
     trait Store for Module<T: Trait> as substraTEERegistry {
         // Simple lists are not supported in runtime modules as theoretically O(n)
@@ -74,24 +223,108 @@ decl_storage! {
         pub EnclaveRegistry get(enclave): linked_map u64 => Enclave<T::AccountId, Vec<u8>>;
         pub EnclaveCount get(enclave_count): u64;
         pub EnclaveIndex get(enclave_index): map T::AccountId => u64;
+        // hash of the RA report the enclave has been registered with
+        pub EnclaveReportHash get(enclave_report_hash): map T::AccountId => T::Hash;
+        // the encoding version of the values in EnclaveRegistry. Chains that registered
+        // enclaves before ENCLAVE_VERSION existed are at 0 and get migrated once.
+        pub EnclaveEncoding get(enclave_encoding) build(|_: &GenesisConfig<T>| ENCLAVE_VERSION): u8;
+        // set if migrating EnclaveRegistry failed. translate has already rewritten the entries
+        // before the failing one, so the migration must not run again.
+        pub EnclaveMigrationFailed get(enclave_migration_failed): bool;
         pub LatestIpfsHash get(latest_ipfs_hash) : map ShardIdentifier => Vec<u8>;
+        // every IPFS hash ever confirmed for a shard, indexed from 1 like the enclaves
+        pub IpfsHashHistory get(ipfs_hash_history) : double_map ShardIdentifier, blake2_256(u64) => Vec<u8>;
+        // number of entries in IpfsHashHistory per shard
+        pub IpfsHashCount get(ipfs_hash_count) : map ShardIdentifier => u64;
+        // all entries in IpfsHashHistory up to this index have been pruned
+        pub IpfsHistoryPrunedTo get(ipfs_history_pruned_to) : map ShardIdentifier => u64;
         // enclave index of the worker that recently committed an update
         pub WorkerForShard get(worker_for_shard) : map ShardIdentifier => u64;
+        // reverse index of WorkerForShard, so that removing an enclave doesn't need to
+        // iterate over all shards.
+        // NOTE: WorkerForShard is a plain map and can't be enumerated, so this index is not
+        // back-filled on upgraded chains. Shards confirmed before the upgrade are only
+        // indexed once their worker confirms them again; until then removing that worker
+        // leaves their WorkerForShard entry pointing at a stale index.
+        pub ShardsForWorker get(shards_for_worker) : map u64 => Vec<ShardIdentifier>;
+        // number of calls each enclave has confirmed so far
+        pub ProcessedCalls get(processed_calls) : map T::AccountId => u64;
+        // if set, only enclaves with this MRENCLAVE may register
+        pub AllowedMrEnclave get(allowed_mr_enclave) : Option<[u8; 32]>;
+        // enclave index the last purge scan stopped at
+        pub PurgeCursor get(purge_cursor) : u64;
+    }
+    add_extra_genesis {
+        // enclaves known at genesis: (pubkey, mr_enclave, timestamp, url)
+        config(enclaves): Vec<(T::AccountId, [u8; 32], i64, Vec<u8>)>;
+        build(|config: &GenesisConfig<T>| {
+            for (pubkey, mr_enclave, timestamp, url) in config.enclaves.iter() {
+                assert!(!<EnclaveIndex<T>>::exists(pubkey), "duplicate enclave in genesis");
+                assert!(url.len() <= T::MaxUrlLen::get() as usize,
+                    "enclave URL too long in genesis");
+                assert!(<Module<T>>::is_valid_url(url), "invalid enclave URL in genesis");
+                let index = <EnclaveCount>::get() + 1;
+                let enclave = Enclave {
+                    pubkey: pubkey.clone(),
+                    mr_enclave: *mr_enclave,
+                    timestamp: *timestamp,
+                    url: url.clone(),
+                };
+                <EnclaveRegistry<T>>::insert(index, &enclave);
+                <EnclaveIndex<T>>::insert(pubkey, index);
+                <EnclaveCount>::put(index);
+            }
+        });
     }
 }
 
 decl_module! {
     pub struct Module<T: Trait> for enum Call where origin: T::Origin {
 
+        /// The maximum length of an RA report in bytes.
+        const MaxRaReportLen: u32 = T::MaxRaReportLen::get();
+
+        /// The maximum length of a worker URL in bytes.
+        const MaxUrlLen: u32 = T::MaxUrlLen::get();
+
+        /// The maximum number of calls that can be confirmed with one `confirm_calls`.
+        const MaxConfirmationsPerBatch: u32 = T::MaxConfirmationsPerBatch::get();
+
+        /// The number of enclaves a registration inspects when looking for a stale one to purge.
+        const MaxPurgeScan: u32 = T::MaxPurgeScan::get();
+
+        /// The number of most recent IPFS hashes kept in the history of each shard.
+        const IpfsHistoryDepth: u64 = T::IpfsHistoryDepth::get();
+
          fn deposit_event() = default;
 
+        fn on_initialize(_now: T::BlockNumber) {
+            if Self::enclave_encoding() < ENCLAVE_VERSION && !Self::enclave_migration_failed() {
+                Self::migrate_enclave_encoding();
+            }
+        }
+
         // the substraTEE-worker wants to register his enclave
-         pub fn register_enclave(origin, ra_report: Vec<u8>, ra_signer_attn: [u32; 16], worker_url: Vec<u8>) -> Result {
+        // `ra_signer_attn` is the sgx_ec256_signature_t (x: [u32; 8], y: [u32; 8]) with which
+        // the enclave's ephemeral RA key signs the extrinsic signer's public key. This proves
+        // that the extrinsic is signed by the attested enclave itself.
+        // An unchanged resubmission of a fresh registration is accepted without verifying the
+        // report again, unless `force` is set.
+         pub fn register_enclave(origin, ra_report: Vec<u8>, ra_signer_attn: [u32; 16], worker_url: Vec<u8>, force: bool) -> Result {
             print_utf8(b"substraTEE_registry: called into runtime call register_enclave()");
             let sender = ensure_signed(origin)?;
-            ensure!(ra_report.len() <= MAX_RA_REPORT_LEN, "RA report too long");
-            ensure!(worker_url.len() <= MAX_URL_LEN, "URL too long");
+            ensure!(!ra_report.is_empty(), Error::EmptyReport);
+            ensure!(ra_report.len() <= T::MaxRaReportLen::get() as usize, "RA report too long");
+            ensure!(worker_url.len() <= T::MaxUrlLen::get() as usize, "URL too long");
+            ensure!(Self::is_valid_url(&worker_url), Error::InvalidUrl);
+            ensure!(Self::is_valid_signer_attn(&ra_signer_attn), Error::InvalidSignerAttestation);
             print_utf8(b"substraTEE_registry: parameter lenght ok");
+            ensure!(<balances::Module<T>>::free_balance(&sender) >= T::MinimumEnclaveBalance::get(),
+                Error::InsufficientEnclaveBalance);
+            if !force && Self::is_unchanged_resubmission(&sender, &ra_report, &worker_url) {
+                print_utf8(b"substraTEE_registry: enclave already registered with this report");
+                return Ok(());
+            }
             match verify_ra_report(&ra_report, &ra_signer_attn.to_vec(), &sender.encode()) {
                 Some(rep) => {
                     print_utf8(b"substraTEE_registry: host_call successful");
@@ -105,22 +338,23 @@ decl_module! {
                     ensure!(sender == enclave_signer,
                         "extrinsic must be signed by attested enclave key");
                     print_utf8(b"substraTEE_registry: signer is a match");
-                    // TODO: activate state checks as soon as we've fixed our setup
-//                    ensure!((report.status == SgxStatus::Ok) | (report.status == SgxStatus::ConfigurationNeeded),
-//                        "RA status is insufficient");
-//                    print_utf8(b"substraTEE_registry: status is acceptable");
+                    ensure!(Self::is_acceptable_status(report.status), Error::InsufficientQuoteStatus);
+                    print_utf8(b"substraTEE_registry: status is acceptable");
+                    ensure!(Self::is_allowed_mr_enclave(&report.mr_enclave), Error::MrEnclaveNotAllowed);
+                    print_utf8(b"substraTEE_registry: MRENCLAVE is allowed");
                     Self::register_verified_enclave(&sender, &report, worker_url.clone())?;
-                    Self::deposit_event(RawEvent::AddedEnclave(sender, worker_url));
+                    <EnclaveReportHash<T>>::insert(&sender, T::Hashing::hash(&ra_report));
+                    Self::deposit_event(RawEvent::AddedEnclave(sender.clone(), worker_url));
                     print_utf8(b"substraTEE_registry: enclave registered");
+                    if T::PurgeStaleEnclaves::get() {
+                        Self::purge_stale_enclave(&sender)?;
+                    }
                     Ok(())
 
                 }
                 None => Err("Verifying RA report failed... returning")
             }
         }
-        // TODO: we can't expect a dead enclave to unregister itself
-        // alternative: allow anyone to unregister an enclave that hasn't recently supplied a RA
-        // such a call should be feeless if successful
         pub fn unregister_enclave(origin) -> Result {
             let sender = ensure_signed(origin)?;
 
@@ -129,6 +363,38 @@ decl_module! {
             Ok(())
         }
 
+        // we can't expect a dead enclave to unregister itself, so anyone may remove an
+        // enclave that hasn't supplied a RA within `MaxEnclaveAge`
+        // TODO: such a call should be feeless if successful
+        pub fn unregister_stale_enclave(origin, index: u64) -> Result {
+            let _sender = ensure_signed(origin)?;
+            ensure!(
+                <EnclaveRegistry<T>>::exists(index),
+                "[SubstraTEERegistry]: Trying to remove an enclave that doesn't exist."
+            );
+            let enclave = Self::enclave(index);
+            ensure!(Self::is_stale(&enclave), Error::EnclaveNotStale);
+
+            Self::remove_enclave(&enclave.pubkey)?;
+            Self::deposit_event(RawEvent::RemovedEnclave(enclave.pubkey));
+            Ok(())
+        }
+
+        // restrict registration to enclaves running the given (audited) build, or lift the
+        // restriction with `None`
+        pub fn set_mr_enclave(origin, mr: Option<[u8; 32]>) -> Result {
+            ensure_root(origin)?;
+            if Self::allowed_mr_enclave() == mr {
+                return Ok(());
+            }
+            match mr {
+                Some(mr) => <AllowedMrEnclave>::put(mr),
+                None => <AllowedMrEnclave>::kill(),
+            }
+            Self::deposit_event(RawEvent::AllowedMrEnclaveChanged(mr));
+            Ok(())
+        }
+
         pub fn call_worker(origin, request: Request) -> Result {
             let _sender = ensure_signed(origin)?;
             Self::deposit_event(RawEvent::Forwarded(request));
@@ -136,22 +402,47 @@ decl_module! {
         }
 
         // the substraTEE-worker calls this function for every processed call to confirm a state update
-         pub fn confirm_call(origin, shard: ShardIdentifier, call_hash: Vec<u8>, ipfs_hash: Vec<u8>) -> Result {
+        // `signature` is the enclave's signature over the encoded `(shard, call_hash, ipfs_hash)`
+        #[weight = SimpleDispatchInfo::FixedNormal(CONFIRMATION_WEIGHT)]
+         pub fn confirm_call(origin, shard: ShardIdentifier, call_hash: Vec<u8>, ipfs_hash: Vec<u8>, signature: T::Signature) -> Result {
             let sender = ensure_signed(origin)?;
-            ensure!(<EnclaveIndex<T>>::exists(&sender),
-            "[SubstraTEERegistry]: IPFS state update requested by enclave that is not registered");
-            let sender_index = Self::enclave_index(&sender);
-            <LatestIpfsHash>::insert(shard, ipfs_hash.clone());
-            <WorkerForShard>::insert(shard, sender_index);
+            Self::confirm_processed_calls(&sender, vec![((shard, call_hash, ipfs_hash), signature)])
+        }
 
-            Self::deposit_event(RawEvent::CallConfirmed(sender, call_hash));
-            Self::deposit_event(RawEvent::UpdatedIpfsHash(shard, sender_index, ipfs_hash));
-            Ok(())
+        // the same as confirm_call, for workers that processed several calls at once
+        #[weight = ConfirmCallsWeight]
+        pub fn confirm_calls(origin, confirmations: Vec<(Confirmation, T::Signature)>) -> Result {
+            let sender = ensure_signed(origin)?;
+            ensure!(!confirmations.is_empty(), Error::NoConfirmations);
+            ensure!(confirmations.len() <= T::MaxConfirmationsPerBatch::get() as usize,
+                Error::TooManyConfirmations);
+            Self::confirm_processed_calls(&sender, confirmations)
         }
     }
 }
 
 impl<T: Trait> Module<T> {
+    /// Re-encodes all enclaves stored in the legacy layout with the current version prefix.
+    fn migrate_enclave_encoding() {
+        let migrated = <EnclaveRegistry<T>>::translate(
+            |index: u64| index,
+            |legacy: LegacyEnclave<T::AccountId, Vec<u8>>| legacy.into(),
+        );
+        match migrated {
+            Ok(()) => <EnclaveEncoding>::put(ENCLAVE_VERSION),
+            Err(_) => {
+                print_utf8(b"substraTEE_registry: failed to migrate the enclave registry");
+                <EnclaveMigrationFailed>::put(true);
+            }
+        }
+    }
+
+    pub fn enclaves() -> Vec<(u64, Enclave<T::AccountId, Vec<u8>>)> {
+        let mut enclaves: Vec<_> = <EnclaveRegistry<T>>::enumerate().collect();
+        enclaves.sort_by_key(|(index, _)| *index);
+        enclaves
+    }
+
     fn register_verified_enclave(
         sender: &T::AccountId,
         report: &SgxReport,
@@ -179,24 +470,170 @@ impl<T: Trait> Module<T> {
         Ok(())
     }
 
+    /// All checks happen before the first storage write so that a failing confirmation
+    /// doesn't leave a partial update behind.
+    fn confirm_processed_calls(
+        sender: &T::AccountId,
+        confirmations: Vec<(Confirmation, T::Signature)>,
+    ) -> Result {
+        ensure!(
+            <EnclaveIndex<T>>::exists(sender),
+            "[SubstraTEERegistry]: IPFS state update requested by enclave that is not registered"
+        );
+        let sender_index = Self::enclave_index(sender);
+        if T::RequireFreshAttestation::get() {
+            ensure!(!Self::is_stale(&Self::enclave(sender_index)), Error::StaleEnclave);
+        }
+        let processed_calls = Self::processed_calls(sender)
+            .checked_add(confirmations.len() as u64)
+            .ok_or("[SubstraTEERegistry]: Overflow counting processed calls")?;
+        for (confirmation, signature) in confirmations.iter() {
+            ensure!(
+                Self::verify_confirmation_signature(sender, confirmation, signature),
+                Error::InvalidConfirmationSignature
+            );
+        }
+
+        for ((shard, call_hash, ipfs_hash), _) in confirmations {
+            let history_index = Self::ipfs_hash_count(shard).saturating_add(1);
+            <IpfsHashHistory>::insert(shard, history_index, ipfs_hash.clone());
+            <IpfsHashCount>::insert(shard, history_index);
+            Self::prune_ipfs_history(shard, history_index);
+            <LatestIpfsHash>::insert(shard, ipfs_hash.clone());
+            Self::assign_shard(shard, sender_index);
+            Self::deposit_event(RawEvent::CallConfirmed(sender.clone(), call_hash));
+            Self::deposit_event(RawEvent::UpdatedIpfsHash(shard, sender_index, ipfs_hash));
+        }
+        <ProcessedCalls<T>>::insert(sender, processed_calls);
+        Self::deposit_event(RawEvent::ProcessedCallsUpdated(sender.clone(), processed_calls));
+        Ok(())
+    }
+
+    /// Removes the entries of a shard's history that are more than `IpfsHistoryDepth`
+    /// behind `latest_index`.
+    fn prune_ipfs_history(shard: ShardIdentifier, latest_index: u64) {
+        let keep_from = latest_index.saturating_sub(T::IpfsHistoryDepth::get());
+        let pruned_to = Self::ipfs_history_pruned_to(shard);
+        if keep_from <= pruned_to {
+            return;
+        }
+        for index in (pruned_to + 1)..=keep_from {
+            <IpfsHashHistory>::remove(shard, index);
+        }
+        <IpfsHistoryPrunedTo>::insert(shard, keep_from);
+        Self::deposit_event(RawEvent::IpfsHistoryPruned(shard, keep_from));
+    }
+
+    /// The enclave's account is its signing key, see `register_enclave`.
+    fn verify_confirmation_signature(
+        enclave: &T::AccountId,
+        confirmation: &Confirmation,
+        signature: &T::Signature,
+    ) -> bool {
+        signature.verify(&confirmation.encode()[..], enclave)
+    }
+
     fn remove_enclave(sender: &T::AccountId) -> Result {
         ensure!(
             <EnclaveIndex<T>>::exists(sender),
             "[SubstraTEERegistry]: Trying to remove an enclave that doesn't exist."
         );
         let index_to_remove = <EnclaveIndex<T>>::take(sender);
+        <EnclaveReportHash<T>>::remove(sender);
 
         let enclaves_count = Self::enclave_count();
         let new_enclaves_count = enclaves_count
             .checked_sub(1)
             .ok_or("[SubstraTEERegistry]: Underflow removing an enclave from the registry")?;
 
+        Self::orphan_shards(index_to_remove);
         Self::swap_and_pop(index_to_remove, new_enclaves_count + 1)?;
         <EnclaveCount>::put(new_enclaves_count);
 
         Ok(())
     }
 
+    /// An enclave resubmitting the very report and URL it is registered with doesn't need to
+    /// be verified again as long as its attestation is fresh.
+    fn is_unchanged_resubmission(sender: &T::AccountId, ra_report: &[u8], url: &[u8]) -> bool {
+        if !<EnclaveIndex<T>>::exists(sender) {
+            return false;
+        }
+        let enclave = Self::enclave(Self::enclave_index(sender));
+        enclave.url == url
+            && Self::enclave_report_hash(sender) == T::Hashing::hash(ra_report)
+            && !Self::is_stale(&enclave)
+            && Self::is_allowed_mr_enclave(&enclave.mr_enclave)
+    }
+
+    fn is_allowed_mr_enclave(mr_enclave: &[u8; 32]) -> bool {
+        Self::allowed_mr_enclave().map_or(true, |allowed| allowed == *mr_enclave)
+    }
+
+    fn is_acceptable_status(status: SgxStatus) -> bool {
+        match status {
+            SgxStatus::Ok | SgxStatus::ConfigurationNeeded => true,
+            SgxStatus::GroupOutOfDate => T::AcceptGroupOutOfDate::get(),
+            _ => false,
+        }
+    }
+
+    fn is_valid_url(url: &[u8]) -> bool {
+        match str::from_utf8(url) {
+            Ok(url) => ["ws://", "wss://", "http://", "https://"]
+                .iter()
+                .any(|scheme| url.starts_with(scheme)),
+            Err(_) => false,
+        }
+    }
+
+    /// A valid ECDSA signature can't have a zero component, so we can reject such
+    /// attestations without calling into the (expensive) RA verification.
+    fn is_valid_signer_attn(signer_attn: &[u32; 16]) -> bool {
+        let (x, y) = signer_attn.split_at(8);
+        x.iter().any(|w| *w != 0) && y.iter().any(|w| *w != 0)
+    }
+
+    /// Removes the stale enclave with the oldest attestation among the next `MaxPurgeScan`
+    /// enclaves, if there is one. Called on registration in order to amortize the cleanup of
+    /// the registry. `PurgeCursor` makes successive registrations cycle through the registry.
+    fn purge_stale_enclave(except: &T::AccountId) -> Result {
+        let count = Self::enclave_count();
+        if count == 0 {
+            return Ok(());
+        }
+        let mut index = Self::purge_cursor();
+        let mut oldest: Option<Enclave<T::AccountId, Vec<u8>>> = None;
+        for _ in 0..count.min(T::MaxPurgeScan::get() as u64) {
+            index = index % count + 1;
+            let enclave = Self::enclave(index);
+            if &enclave.pubkey != except
+                && Self::is_stale(&enclave)
+                && oldest.as_ref().map_or(true, |o| enclave.timestamp < o.timestamp)
+            {
+                oldest = Some(enclave);
+            }
+        }
+        <PurgeCursor>::put(index);
+        if let Some(enclave) = oldest {
+            print_utf8(b"substraTEE_registry: purging stale enclave");
+            Self::remove_enclave(&enclave.pubkey)?;
+            Self::deposit_event(RawEvent::RemovedEnclave(enclave.pubkey));
+        }
+        Ok(())
+    }
+
+    /// Unix epoch in seconds according to the timestamp of the current block.
+    fn now_unix_secs() -> i64 {
+        let now_ms: u64 = <timestamp::Module<T>>::now().saturated_into();
+        (now_ms / 1000).saturated_into()
+    }
+
+    fn is_stale(enclave: &Enclave<T::AccountId, Vec<u8>>) -> bool {
+        Self::now_unix_secs().saturating_sub(enclave.timestamp)
+            > T::MaxEnclaveAge::get().saturated_into::<i64>()
+    }
+
     /// Our list implementation would introduce holes in out list if if we try to remove elements from the middle.
     /// As the order of the enclave entries is not important, we use the swap an pop method to remove elements from
     /// the registry.
@@ -205,12 +642,40 @@ impl<T: Trait> Module<T> {
             let last_enclave = <EnclaveRegistry<T>>::get(&new_enclaves_count);
             <EnclaveRegistry<T>>::insert(index_to_remove, &last_enclave);
             <EnclaveIndex<T>>::insert(last_enclave.pubkey, index_to_remove);
+            // shards served by the moved enclave follow it to its new index
+            let moved_shards = <ShardsForWorker>::take(new_enclaves_count);
+            for shard in moved_shards.iter() {
+                <WorkerForShard>::insert(shard, index_to_remove);
+            }
+            <ShardsForWorker>::insert(index_to_remove, moved_shards);
         }
 
         <EnclaveRegistry<T>>::remove(new_enclaves_count);
 
         Ok(())
     }
+
+    /// Makes `worker_index` the worker of `shard`, keeping `ShardsForWorker` in sync.
+    fn assign_shard(shard: ShardIdentifier, worker_index: u64) {
+        if <WorkerForShard>::exists(shard) {
+            let previous = Self::worker_for_shard(shard);
+            if previous == worker_index {
+                return;
+            }
+            <ShardsForWorker>::mutate(previous, |shards| shards.retain(|s| *s != shard));
+        }
+        <WorkerForShard>::insert(shard, worker_index);
+        <ShardsForWorker>::mutate(worker_index, |shards| shards.push(shard));
+    }
+
+    /// Shards served by the removed enclave are orphaned as we don't track any other worker
+    /// for them.
+    fn orphan_shards(removed_index: u64) {
+        for shard in <ShardsForWorker>::take(removed_index) {
+            <WorkerForShard>::remove(shard);
+            Self::deposit_event(RawEvent::ShardOrphaned(shard));
+        }
+    }
 }
 
 #[cfg(test)]
@@ -223,7 +688,7 @@ mod tests {
     use sr_primitives::weights::Weight;
     use sr_primitives::{
         testing::Header,
-        traits::{BlakeTwo256, IdentifyAccount, IdentityLookup, Verify},
+        traits::{BlakeTwo256, IdentifyAccount, IdentityLookup, OnInitialize, Verify},
         Perbill,
     };
     use std::{cell::RefCell, collections::HashSet};
@@ -232,6 +697,11 @@ mod tests {
 
     thread_local! {
         static EXISTENTIAL_DEPOSIT: RefCell<u64> = RefCell::new(0);
+        static MINIMUM_ENCLAVE_BALANCE: RefCell<u64> = RefCell::new(0);
+        static PURGE_STALE_ENCLAVES: RefCell<bool> = RefCell::new(false);
+        // all our test certs are GROUP_OUT_OF_DATE
+        static ACCEPT_GROUP_OUT_OF_DATE: RefCell<bool> = RefCell::new(true);
+        static REQUIRE_FRESH_ATTESTATION: RefCell<bool> = RefCell::new(false);
     }
     //pub type AccountId = u64;
     pub type BlockNumber = u64;
@@ -242,6 +712,30 @@ mod tests {
             EXISTENTIAL_DEPOSIT.with(|v| *v.borrow())
         }
     }
+    pub struct MinimumEnclaveBalance;
+    impl Get<u64> for MinimumEnclaveBalance {
+        fn get() -> u64 {
+            MINIMUM_ENCLAVE_BALANCE.with(|v| *v.borrow())
+        }
+    }
+    pub struct PurgeStaleEnclaves;
+    impl Get<bool> for PurgeStaleEnclaves {
+        fn get() -> bool {
+            PURGE_STALE_ENCLAVES.with(|v| *v.borrow())
+        }
+    }
+    pub struct AcceptGroupOutOfDate;
+    impl Get<bool> for AcceptGroupOutOfDate {
+        fn get() -> bool {
+            ACCEPT_GROUP_OUT_OF_DATE.with(|v| *v.borrow())
+        }
+    }
+    pub struct RequireFreshAttestation;
+    impl Get<bool> for RequireFreshAttestation {
+        fn get() -> bool {
+            REQUIRE_FRESH_ATTESTATION.with(|v| *v.borrow())
+        }
+    }
 
     // reproduce with "substratee_worker dump_ra"
     const TEST1_CERT: &[u8] =
@@ -292,7 +786,35 @@ mod tests {
     pub struct TestRuntime;
     impl Trait for TestRuntime {
         type Event = TestEvent;
+        type MinimumEnclaveBalance = MinimumEnclaveBalance;
+        type MaxEnclaveAge = MaxEnclaveAge;
+        type PurgeStaleEnclaves = PurgeStaleEnclaves;
+        type MaxPurgeScan = MaxPurgeScan;
+        type MaxRaReportLen = MaxRaReportLen;
+        type MaxUrlLen = MaxUrlLen;
+        type MaxConfirmationsPerBatch = MaxConfirmationsPerBatch;
+        type IpfsHistoryDepth = IpfsHistoryDepth;
+        type AcceptGroupOutOfDate = AcceptGroupOutOfDate;
+        type RequireFreshAttestation = RequireFreshAttestation;
+        type Signature = Signature;
+        type EnclaveSigner = AccountPublic;
+    }
+
+    parameter_types! {
+        pub const MaxEnclaveAge: u64 = 24 * 60 * 60;
+        pub const MaxRaReportLen: u32 = 4096;
+        pub const MaxUrlLen: u32 = 256;
+        pub const MaxConfirmationsPerBatch: u32 = 4;
+        pub const MaxPurgeScan: u32 = 2;
+        pub const IpfsHistoryDepth: u64 = 3;
+        pub const MinimumPeriod: u64 = 1;
+    }
+    impl timestamp::Trait for TestRuntime {
+        type Moment = u64;
+        type OnTimestampSet = ();
+        type MinimumPeriod = MinimumPeriod;
     }
+    pub type Timestamp = timestamp::Module<TestRuntime>;
 
     parameter_types! {
         pub const BlockHashCount: u64 = 250;
@@ -360,6 +882,38 @@ mod tests {
         }
     }
 
+    // EnclaveRegistry as stored before ENCLAVE_VERSION was introduced, to seed legacy entries
+    mod legacy_registry {
+        use super::super::{LegacyEnclave, Trait};
+        use support::{decl_module, decl_storage};
+
+        decl_module! {
+            pub struct Module<T: Trait> for enum Call where origin: T::Origin {}
+        }
+
+        decl_storage! {
+            trait Store for Module<T: Trait> as substraTEERegistry {
+                pub EnclaveRegistry: linked_map u64 => LegacyEnclave<T::AccountId, Vec<u8>>;
+            }
+        }
+    }
+
+    // EnclaveRegistry with entries that decode neither as Enclave nor as LegacyEnclave
+    mod corrupt_registry {
+        use super::super::Trait;
+        use support::{decl_module, decl_storage};
+
+        decl_module! {
+            pub struct Module<T: Trait> for enum Call where origin: T::Origin {}
+        }
+
+        decl_storage! {
+            trait Store for Module<T: Trait> as substraTEERegistry {
+                pub EnclaveRegistry: linked_map u64 => u8;
+            }
+        }
+    }
+
     mod generic_event {
         pub use super::super::Event;
     }
@@ -421,11 +975,188 @@ mod tests {
         (signer, signer_attn)
     }
 
+    // the keys of the TEST enclaves aren't available to sign confirmations, so we
+    // register enclaves for locally generated keys without going through RA
+    fn register_keyed_enclave(seed: u8) -> (sr25519::Pair, AccountId) {
+        let pair = sr25519::Pair::from_seed(&[seed; 32]);
+        let signer: AccountId = AccountPublic::from(pair.public()).into_account();
+        let report = SgxReport {
+            mr_enclave: TEST1_MRENCLAVE,
+            pubkey: pair.public().0,
+            status: SgxStatus::Ok,
+            timestamp: TEST1_TIMESTAMP,
+        };
+        assert_ok!(Registry::register_verified_enclave(&signer, &report, URL.to_vec()));
+        (pair, signer)
+    }
+
+    fn sign_confirmation(pair: &sr25519::Pair, confirmation: &Confirmation) -> Signature {
+        pair.sign(&confirmation.encode()).into()
+    }
+
+    fn sign_confirmations(
+        pair: &sr25519::Pair,
+        confirmations: &[Confirmation],
+    ) -> Vec<(Confirmation, Signature)> {
+        confirmations
+            .iter()
+            .map(|c| (c.clone(), sign_confirmation(pair, c)))
+            .collect()
+    }
+
+    fn confirm_signed_call(
+        pair: &sr25519::Pair,
+        shard: ShardIdentifier,
+        call_hash: Vec<u8>,
+        ipfs_hash: Vec<u8>,
+    ) -> Result {
+        let signer: AccountId = AccountPublic::from(pair.public()).into_account();
+        let signature = sign_confirmation(pair, &(shard, call_hash.clone(), ipfs_hash.clone()));
+        Registry::confirm_call(Origin::signed(signer), shard, call_hash, ipfs_hash, signature)
+    }
+
     fn list_enclaves() -> Vec<(u64, Enclave<AccountId, Vec<u8>>)> {
         <EnclaveRegistry<TestRuntime>>::enumerate()
             .collect::<Vec<(u64, Enclave<AccountId, Vec<u8>>)>>()
     }
 
+    // a hypothetical future layout, to check that versions can be told apart
+    #[derive(Encode, Decode, Clone, PartialEq, Debug)]
+    struct EnclaveV2 {
+        pubkey: AccountId,
+        mr_enclave: [u8; 32],
+        mr_signer: [u8; 32],
+        timestamp: i64,
+        url: Vec<u8>,
+    }
+
+    #[derive(PartialEq, Debug)]
+    enum AnyEnclave {
+        V1(Enclave<AccountId, Vec<u8>>),
+        V2(EnclaveV2),
+    }
+
+    fn decode_any_enclave(encoded: &[u8]) -> Option<AnyEnclave> {
+        match encoded.first() {
+            Some(&ENCLAVE_VERSION) => Enclave::decode(&mut &encoded[..]).ok().map(AnyEnclave::V1),
+            Some(2) => EnclaveV2::decode(&mut &encoded[1..]).ok().map(AnyEnclave::V2),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn enclave_encoding_round_trip_works() {
+        let (signer, _) = get_signer1();
+        let enclave: Enclave<AccountId, Vec<u8>> = Enclave {
+            pubkey: signer,
+            mr_enclave: TEST1_MRENCLAVE,
+            timestamp: TEST1_TIMESTAMP,
+            url: URL.to_vec(),
+        };
+        let encoded = enclave.encode();
+        assert_eq!(encoded[0], ENCLAVE_VERSION);
+        assert_eq!(Enclave::decode(&mut &encoded[..]).unwrap(), enclave);
+    }
+
+    #[test]
+    fn enclave_versions_can_be_told_apart() {
+        let (signer, _) = get_signer1();
+        let v1: Enclave<AccountId, Vec<u8>> = Enclave {
+            pubkey: signer.clone(),
+            mr_enclave: TEST1_MRENCLAVE,
+            timestamp: TEST1_TIMESTAMP,
+            url: URL.to_vec(),
+        };
+        let v2 = EnclaveV2 {
+            pubkey: signer,
+            mr_enclave: TEST1_MRENCLAVE,
+            mr_signer: [1u8; 32],
+            timestamp: TEST1_TIMESTAMP,
+            url: URL.to_vec(),
+        };
+        let mut encoded_v2 = vec![2u8];
+        encoded_v2.extend(v2.encode());
+
+        assert!(Enclave::<AccountId, Vec<u8>>::decode(&mut &encoded_v2[..]).is_err());
+        assert_eq!(
+            decode_any_enclave(&v1.encode()),
+            Some(AnyEnclave::V1(v1.clone()))
+        );
+        assert_eq!(decode_any_enclave(&encoded_v2), Some(AnyEnclave::V2(v2)));
+    }
+
+    #[test]
+    fn legacy_enclave_converts_to_current_encoding() {
+        let (signer, _) = get_signer1();
+        let legacy: LegacyEnclave<AccountId, Vec<u8>> = LegacyEnclave {
+            pubkey: signer.clone(),
+            mr_enclave: TEST1_MRENCLAVE,
+            timestamp: TEST1_TIMESTAMP,
+            url: URL.to_vec(),
+        };
+        let decoded = LegacyEnclave::decode(&mut &legacy.encode()[..]).unwrap();
+        let enclave: Enclave<AccountId, Vec<u8>> = decoded.into();
+
+        assert_eq!(enclave.pubkey, signer);
+        assert_eq!(enclave.mr_enclave, TEST1_MRENCLAVE);
+        assert_eq!(enclave.timestamp, TEST1_TIMESTAMP);
+        assert_eq!(enclave.url, URL.to_vec());
+        assert_eq!(enclave.encode()[0], ENCLAVE_VERSION);
+    }
+
+    fn legacy_enclave(
+        pubkey: &AccountId,
+        mr_enclave: [u8; 32],
+        timestamp: i64,
+    ) -> LegacyEnclave<AccountId, Vec<u8>> {
+        LegacyEnclave {
+            pubkey: pubkey.clone(),
+            mr_enclave,
+            timestamp,
+            url: URL.to_vec(),
+        }
+    }
+
+    #[test]
+    fn on_initialize_migrates_legacy_enclave_encoding_once() {
+        ExtBuilder::build().execute_with(|| {
+            let (signer1, _) = get_signer1();
+            let (signer2, _) = get_signer2();
+            <legacy_registry::EnclaveRegistry<TestRuntime>>::insert(
+                1,
+                &legacy_enclave(&signer1, TEST1_MRENCLAVE, TEST1_TIMESTAMP),
+            );
+            <legacy_registry::EnclaveRegistry<TestRuntime>>::insert(
+                2,
+                &legacy_enclave(&signer2, TEST2_MRENCLAVE, TEST2_TIMESTAMP),
+            );
+            let expected: Vec<(u64, Enclave<AccountId, Vec<u8>>)> = vec![
+                (1, legacy_enclave(&signer1, TEST1_MRENCLAVE, TEST1_TIMESTAMP).into()),
+                (2, legacy_enclave(&signer2, TEST2_MRENCLAVE, TEST2_TIMESTAMP).into()),
+            ];
+            assert_eq!(Registry::enclave_encoding(), 0);
+
+            Registry::on_initialize(1);
+            assert_eq!(Registry::enclave_encoding(), ENCLAVE_VERSION);
+            assert_eq!(Registry::enclaves(), expected);
+
+            // migrating again would decode the versioned entries as legacy ones
+            Registry::on_initialize(2);
+            assert_eq!(Registry::enclaves(), expected);
+        })
+    }
+
+    #[test]
+    fn failed_enclave_migration_is_not_recorded_as_done() {
+        ExtBuilder::build().execute_with(|| {
+            <corrupt_registry::EnclaveRegistry>::insert(1, 7u8);
+
+            Registry::on_initialize(1);
+            assert_eq!(Registry::enclave_encoding(), 0);
+            assert!(Registry::enclave_migration_failed());
+        })
+    }
+
     #[test]
     fn add_enclave_works() {
         ExtBuilder::build().execute_with(|| {
@@ -434,7 +1165,8 @@ mod tests {
                 Origin::signed(signer),
                 TEST1_CERT.to_vec(),
                 signer_attn,
-                URL.to_vec()
+                URL.to_vec(),
+                false
             ));
             assert_eq!(Registry::enclave_count(), 1);
         })
@@ -448,7 +1180,8 @@ mod tests {
                 Origin::signed(signer.clone()),
                 TEST1_CERT.to_vec(),
                 signer_attn,
-                URL.to_vec()
+                URL.to_vec(),
+                false
             ));
             assert_eq!(Registry::enclave_count(), 1);
             assert_ok!(Registry::unregister_enclave(Origin::signed(signer)));
@@ -471,7 +1204,8 @@ mod tests {
                 Origin::signed(signer.clone()),
                 TEST1_CERT.to_vec(),
                 signer_attn,
-                URL.to_vec()
+                URL.to_vec(),
+                false
             ));
             assert_eq!(Registry::enclave_count(), 1);
             let enclaves = list_enclaves();
@@ -512,7 +1246,8 @@ mod tests {
                 Origin::signed(signer1.clone()),
                 TEST1_CERT.to_vec(),
                 signer_attn1,
-                URL.to_vec()
+                URL.to_vec(),
+                false
             ));
             assert_eq!(Registry::enclave_count(), 1);
             assert_eq!(list_enclaves(), vec![(1, e_1.clone())]);
@@ -522,7 +1257,8 @@ mod tests {
                 Origin::signed(signer2.clone()),
                 TEST2_CERT.to_vec(),
                 signer_attn2,
-                URL.to_vec()
+                URL.to_vec(),
+                false
             ));
             assert_eq!(Registry::enclave_count(), 2);
             assert_eq!(list_enclaves(), vec![(2, e_2.clone()), (1, e_1.clone())]);
@@ -532,7 +1268,8 @@ mod tests {
                 Origin::signed(signer3.clone()),
                 TEST3_CERT.to_vec(),
                 signer_attn3,
-                URL.to_vec()
+                URL.to_vec(),
+                false
             ));
             assert_eq!(Registry::enclave_count(), 3);
             assert_eq!(
@@ -548,76 +1285,537 @@ mod tests {
     }
 
     #[test]
-    fn register_invalid_enclave_fails() {
-        let (signer, signer_attn) = get_signer1();
-        assert!(
-            Registry::register_enclave(
-                Origin::signed(signer),
-                Vec::new(),
-                [0u32; 16],
-                URL.to_vec()
-            )
-            .is_err(),
-            URL.to_vec()
-        );
-    }
-
-    #[test]
-    fn update_enclave_url_works() {
+    fn register_enclave_with_allowed_mrenclave_works() {
         ExtBuilder::build().execute_with(|| {
             let (signer, signer_attn) = get_signer1();
-            let url2 = "my fancy url".as_bytes();
-            let e_1: Enclave<AccountId, Vec<u8>> = Enclave {
-                pubkey: signer.clone(),
-                mr_enclave: TEST1_MRENCLAVE,
-                timestamp: TEST1_TIMESTAMP,
-                url: url2.to_vec(),
-            };
-
+            assert_ok!(Registry::set_mr_enclave(Origin::ROOT, Some(TEST1_MRENCLAVE)));
+            assert_eq!(Registry::allowed_mr_enclave(), Some(TEST1_MRENCLAVE));
             assert_ok!(Registry::register_enclave(
-                Origin::signed(signer.clone()),
+                Origin::signed(signer),
                 TEST1_CERT.to_vec(),
                 signer_attn,
-                URL.to_vec()
+                URL.to_vec(),
+                false
             ));
-            assert_eq!(Registry::enclave(1).url, URL.to_vec());
+            assert_eq!(Registry::enclave_count(), 1);
+        })
+    }
 
-            assert_ok!(Registry::register_enclave(
-                Origin::signed(signer.clone()),
-                TEST1_CERT.to_vec(),
-                signer_attn,
-                url2.to_vec()
-            ));
-            assert_eq!(Registry::enclave(1).url, url2.to_vec());
-            let enclaves = list_enclaves();
-            assert_eq!(enclaves[0].1.pubkey, signer)
+    #[test]
+    fn register_enclave_with_other_mrenclave_fails() {
+        ExtBuilder::build().execute_with(|| {
+            let (signer, signer_attn) = get_signer2();
+            assert_ne!(TEST1_MRENCLAVE, TEST2_MRENCLAVE);
+            assert_ok!(Registry::set_mr_enclave(Origin::ROOT, Some(TEST1_MRENCLAVE)));
+            assert_eq!(
+                Registry::register_enclave(
+                    Origin::signed(signer),
+                    TEST2_CERT.to_vec(),
+                    signer_attn,
+                    URL.to_vec(),
+                    false
+                ),
+                Err(Error::MrEnclaveNotAllowed.into())
+            );
+            assert_eq!(Registry::enclave_count(), 0);
         })
     }
 
     #[test]
-    fn update_ipfs_hash_works() {
+    fn set_mr_enclave_requires_root() {
         ExtBuilder::build().execute_with(|| {
-            let ipfs_hash = "QmYY9U7sQzBYe79tVfiMyJ4prEJoJRWCD8t85j9qjssS9y";
-            let shard = H256::default();
-            let request_hash = vec![];
-            let (signer, signer_attn) = get_signer1();
+            let (signer, _) = get_signer1();
+            assert!(Registry::set_mr_enclave(Origin::signed(signer), Some(TEST1_MRENCLAVE)).is_err());
+            assert_eq!(Registry::allowed_mr_enclave(), None);
+        })
+    }
+
+    #[test]
+    fn set_mr_enclave_to_none_lifts_restriction() {
+        ExtBuilder::build().execute_with(|| {
+            let (signer, signer_attn) = get_signer2();
+            assert_ok!(Registry::set_mr_enclave(Origin::ROOT, Some(TEST1_MRENCLAVE)));
+            assert_ok!(Registry::set_mr_enclave(Origin::ROOT, None));
+            assert_eq!(Registry::allowed_mr_enclave(), None);
 
+            let expected_event =
+                TestEvent::generic_event(RawEvent::AllowedMrEnclaveChanged(None));
+            assert!(System::events().iter().any(|a| a.event == expected_event));
             assert_ok!(Registry::register_enclave(
-                Origin::signed(signer.clone()),
-                TEST1_CERT.to_vec(),
+                Origin::signed(signer),
+                TEST2_CERT.to_vec(),
                 signer_attn,
-                URL.to_vec()
+                URL.to_vec(),
+                false
             ));
             assert_eq!(Registry::enclave_count(), 1);
-            assert_ok!(Registry::confirm_call(
-                Origin::signed(signer.clone()),
-                shard.clone(),
-                request_hash.clone(),
-                ipfs_hash.as_bytes().to_vec()
-            ));
-            assert_eq!(
-                str::from_utf8(&Registry::latest_ipfs_hash(shard.clone())).unwrap(),
-                ipfs_hash
+        })
+    }
+
+    #[test]
+    fn set_unchanged_mr_enclave_emits_no_event() {
+        ExtBuilder::build().execute_with(|| {
+            assert_ok!(Registry::set_mr_enclave(Origin::ROOT, Some(TEST1_MRENCLAVE)));
+            assert_eq!(System::events().len(), 1);
+            assert_ok!(Registry::set_mr_enclave(Origin::ROOT, Some(TEST1_MRENCLAVE)));
+            assert_eq!(System::events().len(), 1);
+        })
+    }
+
+    #[test]
+    fn enclaves_lists_all_enclaves_by_index() {
+        ExtBuilder::build().execute_with(|| {
+            assert!(Registry::enclaves().is_empty());
+            let (_, signer1) = register_keyed_enclave(1);
+            let (_, signer2) = register_keyed_enclave(2);
+            let (_, signer3) = register_keyed_enclave(3);
+            let pubkeys: Vec<(u64, AccountId)> = Registry::enclaves()
+                .into_iter()
+                .map(|(index, enclave)| (index, enclave.pubkey))
+                .collect();
+            assert_eq!(pubkeys, vec![(1, signer1), (2, signer2), (3, signer3)]);
+        })
+    }
+
+    #[test]
+    fn genesis_enclaves_are_registered() {
+        let (signer1, _) = get_signer1();
+        let (signer2, _) = get_signer2();
+        let mut storage = system::GenesisConfig::default()
+            .build_storage::<TestRuntime>()
+            .unwrap();
+        GenesisConfig::<TestRuntime> {
+            enclaves: vec![
+                (signer1.clone(), TEST1_MRENCLAVE, TEST1_TIMESTAMP, URL.to_vec()),
+                (signer2.clone(), TEST2_MRENCLAVE, TEST2_TIMESTAMP, URL.to_vec()),
+            ],
+        }
+        .assimilate_storage(&mut storage)
+        .unwrap();
+
+        runtime_io::TestExternalities::from(storage).execute_with(|| {
+            assert_eq!(Registry::enclave_count(), 2);
+            assert_eq!(Registry::enclave_index(&signer1), 1);
+            assert_eq!(Registry::enclave_index(&signer2), 2);
+            let enclave = Registry::enclave(2);
+            assert_eq!(enclave.pubkey, signer2);
+            assert_eq!(enclave.mr_enclave, TEST2_MRENCLAVE);
+            assert_eq!(enclave.timestamp, TEST2_TIMESTAMP);
+            assert_eq!(enclave.url, URL.to_vec());
+            // new chains start with the current encoding, so there is nothing to migrate
+            assert_eq!(Registry::enclave_encoding(), ENCLAVE_VERSION);
+            Registry::on_initialize(1);
+            assert_eq!(Registry::enclave(2), enclave);
+        })
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid enclave URL in genesis")]
+    fn genesis_enclave_with_invalid_url_fails() {
+        let (signer1, _) = get_signer1();
+        let mut storage = system::GenesisConfig::default()
+            .build_storage::<TestRuntime>()
+            .unwrap();
+        GenesisConfig::<TestRuntime> {
+            enclaves: vec![(
+                signer1,
+                TEST1_MRENCLAVE,
+                TEST1_TIMESTAMP,
+                b"127.0.0.1:9991".to_vec(),
+            )],
+        }
+        .assimilate_storage(&mut storage)
+        .unwrap();
+    }
+
+    #[test]
+    fn register_enclaves_with_same_mrenclave_works() {
+        ExtBuilder::build().execute_with(|| {
+            // TEST2 and TEST3 run the same enclave on different signer keys
+            let (signer2, signer_attn2) = get_signer2();
+            let (signer3, signer_attn3) = get_signer3();
+            assert_eq!(TEST2_MRENCLAVE, TEST3_MRENCLAVE);
+
+            assert_ok!(Registry::register_enclave(
+                Origin::signed(signer2.clone()),
+                TEST2_CERT.to_vec(),
+                signer_attn2,
+                URL.to_vec(),
+                false
+            ));
+            assert_ok!(Registry::register_enclave(
+                Origin::signed(signer3.clone()),
+                TEST3_CERT.to_vec(),
+                signer_attn3,
+                URL.to_vec(),
+                false
+            ));
+            assert_eq!(Registry::enclave_count(), 2);
+            assert_eq!(Registry::enclave_index(&signer2), 1);
+            assert_eq!(Registry::enclave_index(&signer3), 2);
+            assert_eq!(Registry::enclave(1).pubkey, signer2);
+            assert_eq!(Registry::enclave(2).pubkey, signer3);
+            assert_eq!(Registry::enclave(1).mr_enclave, TEST2_MRENCLAVE);
+            assert_eq!(Registry::enclave(2).mr_enclave, TEST2_MRENCLAVE);
+        })
+    }
+
+    #[test]
+    fn register_invalid_enclave_fails() {
+        ExtBuilder::build().execute_with(|| {
+            let (signer, signer_attn) = get_signer1();
+            assert_eq!(
+                Registry::register_enclave(
+                    Origin::signed(signer),
+                    Vec::new(),
+                    [0u32; 16],
+                    URL.to_vec(),
+                    false
+                ),
+                Err(Error::EmptyReport.into())
+            );
+        })
+    }
+
+    #[test]
+    fn register_enclave_with_too_long_report_fails() {
+        ExtBuilder::build().execute_with(|| {
+            let (signer, signer_attn) = get_signer1();
+            let mut report = TEST1_CERT.to_vec();
+            report.resize(MaxRaReportLen::get() as usize + 1, 0);
+            assert_eq!(
+                Registry::register_enclave(
+                    Origin::signed(signer),
+                    report,
+                    signer_attn,
+                    URL.to_vec(),
+                    false
+                ),
+                Err("RA report too long")
+            );
+        })
+    }
+
+    #[test]
+    fn register_enclave_with_too_long_url_fails() {
+        ExtBuilder::build().execute_with(|| {
+            let (signer, signer_attn) = get_signer1();
+            let mut url = URL.to_vec();
+            url.resize(MaxUrlLen::get() as usize + 1, b'1');
+            assert_eq!(
+                Registry::register_enclave(
+                    Origin::signed(signer),
+                    TEST1_CERT.to_vec(),
+                    signer_attn,
+                    url,
+                    false
+                ),
+                Err("URL too long")
+            );
+        })
+    }
+
+    #[test]
+    fn register_enclave_with_non_utf8_url_fails() {
+        ExtBuilder::build().execute_with(|| {
+            let (signer, signer_attn) = get_signer1();
+            let mut url = URL.to_vec();
+            url.extend_from_slice(&[0xff, 0xfe]);
+            assert_eq!(
+                Registry::register_enclave(
+                    Origin::signed(signer),
+                    TEST1_CERT.to_vec(),
+                    signer_attn,
+                    url,
+                    false
+                ),
+                Err(Error::InvalidUrl.into())
+            );
+            assert_eq!(Registry::enclave_count(), 0);
+        })
+    }
+
+    #[test]
+    fn register_enclave_with_url_without_scheme_fails() {
+        ExtBuilder::build().execute_with(|| {
+            let (signer, signer_attn) = get_signer1();
+            assert_eq!(
+                Registry::register_enclave(
+                    Origin::signed(signer),
+                    TEST1_CERT.to_vec(),
+                    signer_attn,
+                    b"127.0.0.1:9991".to_vec(),
+                    false
+                ),
+                Err(Error::InvalidUrl.into())
+            );
+            assert_eq!(Registry::enclave_count(), 0);
+        })
+    }
+
+    #[test]
+    fn is_valid_url_works() {
+        for url in &["ws://a:1", "wss://a:1", "http://a", "https://a"] {
+            assert!(Registry::is_valid_url(url.as_bytes()));
+        }
+        for url in &["", "ftp://a", "a:1", "WS://a", " ws://a"] {
+            assert!(!Registry::is_valid_url(url.as_bytes()));
+        }
+    }
+
+    #[test]
+    fn register_enclave_with_zero_signer_attn_fails() {
+        ExtBuilder::build().execute_with(|| {
+            let (signer, _) = get_signer1();
+            assert_eq!(
+                Registry::register_enclave(
+                    Origin::signed(signer),
+                    TEST1_CERT.to_vec(),
+                    [0u32; 16],
+                    URL.to_vec(),
+                    false
+                ),
+                Err(Error::InvalidSignerAttestation.into())
+            );
+            assert_eq!(Registry::enclave_count(), 0);
+        })
+    }
+
+    #[test]
+    fn register_enclave_with_half_zero_signer_attn_fails() {
+        ExtBuilder::build().execute_with(|| {
+            let (signer, mut signer_attn) = get_signer1();
+            signer_attn[8..].copy_from_slice(&[0u32; 8]);
+            assert_eq!(
+                Registry::register_enclave(
+                    Origin::signed(signer),
+                    TEST1_CERT.to_vec(),
+                    signer_attn,
+                    URL.to_vec(),
+                    false
+                ),
+                Err(Error::InvalidSignerAttestation.into())
+            );
+        })
+    }
+
+    #[test]
+    fn register_enclave_with_group_out_of_date_fails_if_not_accepted() {
+        ExtBuilder::build().execute_with(|| {
+            ACCEPT_GROUP_OUT_OF_DATE.with(|v| *v.borrow_mut() = false);
+            let (signer, signer_attn) = get_signer1();
+            assert_eq!(
+                Registry::register_enclave(
+                    Origin::signed(signer),
+                    TEST1_CERT.to_vec(),
+                    signer_attn,
+                    URL.to_vec(),
+                    false
+                ),
+                Err(Error::InsufficientQuoteStatus.into())
+            );
+            assert_eq!(Registry::enclave_count(), 0);
+        })
+    }
+
+    #[test]
+    fn acceptable_quote_status_works() {
+        assert!(Registry::is_acceptable_status(SgxStatus::Ok));
+        assert!(Registry::is_acceptable_status(SgxStatus::ConfigurationNeeded));
+        assert!(Registry::is_acceptable_status(SgxStatus::GroupOutOfDate));
+        assert!(!Registry::is_acceptable_status(SgxStatus::GroupRevoked));
+        assert!(!Registry::is_acceptable_status(SgxStatus::Invalid));
+        ACCEPT_GROUP_OUT_OF_DATE.with(|v| *v.borrow_mut() = false);
+        assert!(!Registry::is_acceptable_status(SgxStatus::GroupOutOfDate));
+    }
+
+    #[test]
+    fn register_enclave_with_balance_below_minimum_fails() {
+        ExtBuilder::build().execute_with(|| {
+            MINIMUM_ENCLAVE_BALANCE.with(|v| *v.borrow_mut() = 100);
+            let (signer, signer_attn) = get_signer1();
+            Balances::make_free_balance_be(&signer, 99);
+            assert_eq!(
+                Registry::register_enclave(
+                    Origin::signed(signer),
+                    TEST1_CERT.to_vec(),
+                    signer_attn,
+                    URL.to_vec(),
+                    false
+                ),
+                Err(Error::InsufficientEnclaveBalance.into())
+            );
+            assert_eq!(Registry::enclave_count(), 0);
+        })
+    }
+
+    #[test]
+    fn register_enclave_with_minimum_balance_works() {
+        ExtBuilder::build().execute_with(|| {
+            MINIMUM_ENCLAVE_BALANCE.with(|v| *v.borrow_mut() = 100);
+            let (signer, signer_attn) = get_signer1();
+            Balances::make_free_balance_be(&signer, 100);
+            assert_ok!(Registry::register_enclave(
+                Origin::signed(signer),
+                TEST1_CERT.to_vec(),
+                signer_attn,
+                URL.to_vec(),
+                false
+            ));
+            assert_eq!(Registry::enclave_count(), 1);
+        })
+    }
+
+    #[test]
+    fn update_enclave_url_works() {
+        ExtBuilder::build().execute_with(|| {
+            let (signer, signer_attn) = get_signer1();
+            let url2 = "wss://my.fancy.url:443".as_bytes();
+            let e_1: Enclave<AccountId, Vec<u8>> = Enclave {
+                pubkey: signer.clone(),
+                mr_enclave: TEST1_MRENCLAVE,
+                timestamp: TEST1_TIMESTAMP,
+                url: url2.to_vec(),
+            };
+
+            assert_ok!(Registry::register_enclave(
+                Origin::signed(signer.clone()),
+                TEST1_CERT.to_vec(),
+                signer_attn,
+                URL.to_vec(),
+                false
+            ));
+            assert_eq!(Registry::enclave(1).url, URL.to_vec());
+
+            assert_ok!(Registry::register_enclave(
+                Origin::signed(signer.clone()),
+                TEST1_CERT.to_vec(),
+                signer_attn,
+                url2.to_vec(),
+                false
+            ));
+            assert_eq!(Registry::enclave(1).url, url2.to_vec());
+            let enclaves = list_enclaves();
+            assert_eq!(enclaves[0].1.pubkey, signer)
+        })
+    }
+
+    #[test]
+    fn unchanged_resubmission_skips_verification() {
+        ExtBuilder::build().execute_with(|| {
+            let (signer, signer_attn) = get_signer1();
+            // this attestation doesn't match TEST1_CERT, so verification would fail
+            let (_, wrong_signer_attn) = get_signer2();
+            assert_ok!(Registry::register_enclave(
+                Origin::signed(signer.clone()),
+                TEST1_CERT.to_vec(),
+                signer_attn,
+                URL.to_vec(),
+                false
+            ));
+            assert_ok!(Registry::register_enclave(
+                Origin::signed(signer.clone()),
+                TEST1_CERT.to_vec(),
+                wrong_signer_attn,
+                URL.to_vec(),
+                false
+            ));
+            assert_eq!(Registry::enclave_count(), 1);
+            let added_event =
+                TestEvent::generic_event(RawEvent::AddedEnclave(signer, URL.to_vec()));
+            assert_eq!(
+                System::events()
+                    .iter()
+                    .filter(|a| a.event == added_event)
+                    .count(),
+                1
+            );
+        })
+    }
+
+    #[test]
+    fn forced_unchanged_resubmission_is_verified() {
+        ExtBuilder::build().execute_with(|| {
+            let (signer, signer_attn) = get_signer1();
+            // this attestation doesn't match TEST1_CERT, so verification fails
+            let (_, wrong_signer_attn) = get_signer2();
+            assert_ok!(Registry::register_enclave(
+                Origin::signed(signer.clone()),
+                TEST1_CERT.to_vec(),
+                signer_attn,
+                URL.to_vec(),
+                false
+            ));
+            assert!(Registry::register_enclave(
+                Origin::signed(signer.clone()),
+                TEST1_CERT.to_vec(),
+                wrong_signer_attn,
+                URL.to_vec(),
+                true
+            )
+            .is_err());
+            assert_ok!(Registry::register_enclave(
+                Origin::signed(signer),
+                TEST1_CERT.to_vec(),
+                signer_attn,
+                URL.to_vec(),
+                true
+            ));
+            assert_eq!(Registry::enclave_count(), 1);
+        })
+    }
+
+    #[test]
+    fn changed_resubmission_is_verified() {
+        ExtBuilder::build().execute_with(|| {
+            let (signer, signer_attn) = get_signer1();
+            let (_, wrong_signer_attn) = get_signer2();
+            assert_ok!(Registry::register_enclave(
+                Origin::signed(signer.clone()),
+                TEST1_CERT.to_vec(),
+                signer_attn,
+                URL.to_vec(),
+                false
+            ));
+            assert!(Registry::register_enclave(
+                Origin::signed(signer.clone()),
+                TEST1_CERT.to_vec(),
+                wrong_signer_attn,
+                "wss://my.fancy.url:443".as_bytes().to_vec(),
+                false
+            )
+            .is_err());
+            // a stale enclave has to be verified again
+            Timestamp::set_timestamp(TEST2_TIMESTAMP as u64 * 1000);
+            assert!(Registry::register_enclave(
+                Origin::signed(signer),
+                TEST1_CERT.to_vec(),
+                wrong_signer_attn,
+                URL.to_vec(),
+                false
+            )
+            .is_err());
+        })
+    }
+
+    #[test]
+    fn update_ipfs_hash_works() {
+        ExtBuilder::build().execute_with(|| {
+            let ipfs_hash = "QmYY9U7sQzBYe79tVfiMyJ4prEJoJRWCD8t85j9qjssS9y";
+            let shard = H256::default();
+            let request_hash = vec![];
+            let (pair, signer) = register_keyed_enclave(1);
+
+            assert_eq!(Registry::enclave_count(), 1);
+            assert_ok!(confirm_signed_call(
+                &pair,
+                shard.clone(),
+                request_hash.clone(),
+                ipfs_hash.as_bytes().to_vec()
+            ));
+            assert_eq!(
+                str::from_utf8(&Registry::latest_ipfs_hash(shard.clone())).unwrap(),
+                ipfs_hash
             );
             assert_eq!(Registry::worker_for_shard(shard.clone()), 1u64);
 
@@ -634,13 +1832,237 @@ mod tests {
         })
     }
 
+    #[test]
+    fn confirm_call_with_tampered_ipfs_hash_fails() {
+        ExtBuilder::build().execute_with(|| {
+            let shard = H256::default();
+            let (pair, signer) = register_keyed_enclave(1);
+            let signature = sign_confirmation(&pair, &(shard, vec![], vec![1, 1]));
+
+            assert_eq!(
+                Registry::confirm_call(
+                    Origin::signed(signer.clone()),
+                    shard,
+                    vec![],
+                    vec![6, 6],
+                    signature
+                ),
+                Err(Error::InvalidConfirmationSignature.into())
+            );
+            assert_eq!(Registry::latest_ipfs_hash(shard), Vec::<u8>::new());
+            assert_eq!(Registry::processed_calls(&signer), 0);
+        })
+    }
+
+    #[test]
+    fn confirm_call_signed_by_other_key_fails() {
+        ExtBuilder::build().execute_with(|| {
+            let shard = H256::default();
+            let (_, signer) = register_keyed_enclave(1);
+            let (other_pair, _) = register_keyed_enclave(2);
+            let signature = sign_confirmation(&other_pair, &(shard, vec![], vec![1, 1]));
+
+            assert_eq!(
+                Registry::confirm_call(Origin::signed(signer), shard, vec![], vec![1, 1], signature),
+                Err(Error::InvalidConfirmationSignature.into())
+            );
+        })
+    }
+
+    #[test]
+    fn confirm_call_by_fresh_enclave_works_if_fresh_attestation_required() {
+        ExtBuilder::build().execute_with(|| {
+            REQUIRE_FRESH_ATTESTATION.with(|v| *v.borrow_mut() = true);
+            let ipfs_hash = "QmYY9U7sQzBYe79tVfiMyJ4prEJoJRWCD8t85j9qjssS9y".as_bytes().to_vec();
+            let shard = H256::default();
+            let (pair, _) = register_keyed_enclave(1);
+
+            Timestamp::set_timestamp(TEST1_TIMESTAMP as u64 * 1000);
+            assert_ok!(confirm_signed_call(&pair, shard, vec![], ipfs_hash.clone()));
+            assert_eq!(Registry::latest_ipfs_hash(shard), ipfs_hash);
+        })
+    }
+
+    #[test]
+    fn confirm_call_by_stale_enclave_fails_if_fresh_attestation_required() {
+        ExtBuilder::build().execute_with(|| {
+            REQUIRE_FRESH_ATTESTATION.with(|v| *v.borrow_mut() = true);
+            let ipfs_hash = "QmYY9U7sQzBYe79tVfiMyJ4prEJoJRWCD8t85j9qjssS9y".as_bytes().to_vec();
+            let shard = H256::default();
+            let (pair, signer) = register_keyed_enclave(1);
+
+            Timestamp::set_timestamp(TEST2_TIMESTAMP as u64 * 1000);
+            assert_eq!(
+                confirm_signed_call(&pair, shard, vec![], ipfs_hash.clone()),
+                Err(Error::StaleEnclave.into())
+            );
+            assert_eq!(Registry::latest_ipfs_hash(shard), Vec::<u8>::new());
+            assert_eq!(Registry::processed_calls(&signer), 0);
+
+            // without the requirement stale enclaves can still confirm
+            REQUIRE_FRESH_ATTESTATION.with(|v| *v.borrow_mut() = false);
+            assert_ok!(confirm_signed_call(&pair, shard, vec![], ipfs_hash));
+        })
+    }
+
+    #[test]
+    fn confirm_call_counts_processed_calls() {
+        ExtBuilder::build().execute_with(|| {
+            let ipfs_hash = "QmYY9U7sQzBYe79tVfiMyJ4prEJoJRWCD8t85j9qjssS9y";
+            let shard = H256::default();
+            let (pair, signer) = register_keyed_enclave(1);
+
+            assert_eq!(Registry::processed_calls(&signer), 0);
+            for _ in 0..2 {
+                assert_ok!(confirm_signed_call(
+                    &pair,
+                    shard.clone(),
+                    vec![],
+                    ipfs_hash.as_bytes().to_vec()
+                ));
+            }
+            assert_eq!(Registry::processed_calls(&signer), 2);
+            let expected_event =
+                TestEvent::generic_event(RawEvent::ProcessedCallsUpdated(signer, 2));
+            assert!(System::events().iter().any(|a| a.event == expected_event));
+        })
+    }
+
+    #[test]
+    fn confirm_calls_works() {
+        ExtBuilder::build().execute_with(|| {
+            let (pair, signer) = register_keyed_enclave(1);
+            let confirmations: Vec<Confirmation> = (1u8..=3)
+                .map(|i| (H256::from([i; 32]), vec![i], vec![i, i]))
+                .collect();
+
+            assert_ok!(Registry::confirm_calls(
+                Origin::signed(signer.clone()),
+                sign_confirmations(&pair, &confirmations)
+            ));
+            for (shard, call_hash, ipfs_hash) in confirmations {
+                assert_eq!(Registry::latest_ipfs_hash(shard), ipfs_hash);
+                assert_eq!(Registry::worker_for_shard(shard), 1u64);
+                let expected_event =
+                    TestEvent::generic_event(RawEvent::CallConfirmed(signer.clone(), call_hash));
+                assert!(System::events().iter().any(|a| a.event == expected_event));
+            }
+            assert_eq!(Registry::processed_calls(&signer), 3);
+        })
+    }
+
+    #[test]
+    fn confirm_calls_with_one_tampered_confirmation_fails() {
+        ExtBuilder::build().execute_with(|| {
+            let (pair, signer) = register_keyed_enclave(1);
+            let confirmations: Vec<Confirmation> = (1u8..=3)
+                .map(|i| (H256::from([i; 32]), vec![i], vec![i, i]))
+                .collect();
+            let mut signed = sign_confirmations(&pair, &confirmations);
+            (signed[2].0).2 = vec![6, 6];
+
+            assert_eq!(
+                Registry::confirm_calls(Origin::signed(signer.clone()), signed),
+                Err(Error::InvalidConfirmationSignature.into())
+            );
+            // nothing of the batch has been applied
+            for (shard, _, _) in confirmations {
+                assert_eq!(Registry::latest_ipfs_hash(shard), Vec::<u8>::new());
+            }
+            assert_eq!(Registry::processed_calls(&signer), 0);
+        })
+    }
+
+    #[test]
+    fn ipfs_hash_history_works() {
+        ExtBuilder::build().execute_with(|| {
+            let shard = H256::default();
+            let (pair, _) = register_keyed_enclave(1);
+
+            assert_ok!(confirm_signed_call(&pair, shard, vec![1], vec![1, 1]));
+            assert_ok!(confirm_signed_call(&pair, shard, vec![2], vec![2, 2]));
+            assert_eq!(Registry::ipfs_hash_count(shard), 2);
+            assert_eq!(Registry::ipfs_hash_history(shard, 1), vec![1, 1]);
+            assert_eq!(Registry::ipfs_hash_history(shard, 2), vec![2, 2]);
+            assert_eq!(Registry::latest_ipfs_hash(shard), vec![2, 2]);
+            assert_eq!(Registry::ipfs_hash_count(H256::from([1; 32])), 0);
+        })
+    }
+
+    #[test]
+    fn ipfs_hash_history_is_pruned_beyond_depth() {
+        ExtBuilder::build().execute_with(|| {
+            let shard = H256::default();
+            let (pair, _) = register_keyed_enclave(1);
+
+            for i in 1u8..=5 {
+                assert_ok!(confirm_signed_call(&pair, shard, vec![i], vec![i, i]));
+            }
+            assert_eq!(IpfsHistoryDepth::get(), 3);
+            assert_eq!(Registry::ipfs_hash_count(shard), 5);
+            assert_eq!(Registry::ipfs_history_pruned_to(shard), 2);
+            for i in 1u8..=2 {
+                assert_eq!(Registry::ipfs_hash_history(shard, i as u64), Vec::<u8>::new());
+            }
+            for i in 3u8..=5 {
+                assert_eq!(Registry::ipfs_hash_history(shard, i as u64), vec![i, i]);
+            }
+            let expected_event = TestEvent::generic_event(RawEvent::IpfsHistoryPruned(shard, 2));
+            assert!(System::events().iter().any(|a| a.event == expected_event));
+        })
+    }
+
+    #[test]
+    fn confirm_calls_with_too_many_confirmations_fails() {
+        ExtBuilder::build().execute_with(|| {
+            let (pair, signer) = register_keyed_enclave(1);
+            let confirmations: Vec<Confirmation> = (0..MaxConfirmationsPerBatch::get() + 1)
+                .map(|i| (H256::from([i as u8; 32]), vec![], vec![]))
+                .collect();
+
+            assert_eq!(
+                Registry::confirm_calls(
+                    Origin::signed(signer.clone()),
+                    sign_confirmations(&pair, &confirmations)
+                ),
+                Err(Error::TooManyConfirmations.into())
+            );
+            assert_eq!(Registry::processed_calls(&signer), 0);
+        })
+    }
+
+    #[test]
+    fn confirm_calls_without_confirmations_fails() {
+        ExtBuilder::build().execute_with(|| {
+            let (_, signer) = register_keyed_enclave(1);
+
+            assert_eq!(
+                Registry::confirm_calls(Origin::signed(signer.clone()), vec![]),
+                Err(Error::NoConfirmations.into())
+            );
+            assert_eq!(Registry::processed_calls(&signer), 0);
+        })
+    }
+
+    #[test]
+    fn confirm_calls_weight_scales_with_confirmations() {
+        let confirmations: Vec<Confirmation> = (0..3)
+            .map(|i| (H256::from([i as u8; 32]), vec![], vec![]))
+            .collect();
+
+        assert_eq!(
+            ConfirmCallsWeight.weigh_data((&confirmations,)),
+            3 * CONFIRMATION_WEIGHT
+        );
+    }
+
     #[test]
     fn ipfs_update_from_unregistered_enclave_fails() {
         ExtBuilder::build().execute_with(|| {
             let ipfs_hash = "QmYY9U7sQzBYe79tVfiMyJ4prEJoJRWCD8t85j9qjssS9y";
-            let (signer, signer_attn) = get_signer1();
-            assert!(Registry::confirm_call(
-                Origin::signed(signer),
+            let pair = sr25519::Pair::from_seed(&[1; 32]);
+            assert!(confirm_signed_call(
+                &pair,
                 H256::default(),
                 vec![],
                 ipfs_hash.as_bytes().to_vec()
@@ -649,6 +2071,213 @@ mod tests {
         })
     }
 
+    #[test]
+    fn unregistering_sole_worker_orphans_shard() {
+        ExtBuilder::build().execute_with(|| {
+            let ipfs_hash = "QmYY9U7sQzBYe79tVfiMyJ4prEJoJRWCD8t85j9qjssS9y";
+            let shard = H256::default();
+            let (pair, signer) = register_keyed_enclave(1);
+
+            assert_ok!(confirm_signed_call(
+                &pair,
+                shard.clone(),
+                vec![],
+                ipfs_hash.as_bytes().to_vec()
+            ));
+            assert!(<WorkerForShard>::exists(shard));
+
+            assert_ok!(Registry::unregister_enclave(Origin::signed(signer)));
+            assert!(!<WorkerForShard>::exists(shard));
+            assert!(Registry::shards_for_worker(1).is_empty());
+            let expected_event = TestEvent::generic_event(RawEvent::ShardOrphaned(shard));
+            assert!(System::events().iter().any(|a| a.event == expected_event));
+        })
+    }
+
+    #[test]
+    fn worker_for_shard_follows_moved_enclave() {
+        ExtBuilder::build().execute_with(|| {
+            let ipfs_hash = "QmYY9U7sQzBYe79tVfiMyJ4prEJoJRWCD8t85j9qjssS9y";
+            let shard = H256::default();
+            let (_, signer1) = register_keyed_enclave(1);
+            let (pair2, signer2) = register_keyed_enclave(2);
+
+            assert_ok!(confirm_signed_call(
+                &pair2,
+                shard.clone(),
+                vec![],
+                ipfs_hash.as_bytes().to_vec()
+            ));
+            assert_eq!(Registry::worker_for_shard(shard), 2u64);
+
+            // enclave 2 is moved to index 1
+            assert_ok!(Registry::unregister_enclave(Origin::signed(signer1)));
+            assert_eq!(Registry::enclave_index(&signer2), 1u64);
+            assert_eq!(Registry::worker_for_shard(shard), 1u64);
+            assert_eq!(Registry::shards_for_worker(1), vec![shard]);
+            assert!(Registry::shards_for_worker(2).is_empty());
+        })
+    }
+
+    #[test]
+    fn shard_taken_over_by_other_worker_is_not_orphaned() {
+        ExtBuilder::build().execute_with(|| {
+            let shard = H256::default();
+            let (pair1, signer1) = register_keyed_enclave(1);
+            let (pair2, _) = register_keyed_enclave(2);
+
+            assert_ok!(confirm_signed_call(&pair1, shard, vec![], vec![1]));
+            assert_ok!(confirm_signed_call(&pair2, shard, vec![], vec![2]));
+            assert_eq!(Registry::worker_for_shard(shard), 2u64);
+            assert!(Registry::shards_for_worker(1).is_empty());
+            assert_eq!(Registry::shards_for_worker(2), vec![shard]);
+
+            // enclave 2 is moved to index 1 and keeps serving the shard
+            assert_ok!(Registry::unregister_enclave(Origin::signed(signer1)));
+            assert_eq!(Registry::worker_for_shard(shard), 1u64);
+            assert_eq!(Registry::shards_for_worker(1), vec![shard]);
+            let orphaned_event = TestEvent::generic_event(RawEvent::ShardOrphaned(shard));
+            assert!(!System::events().iter().any(|a| a.event == orphaned_event));
+        })
+    }
+
+    #[test]
+    fn registering_purges_stale_enclave_if_enabled() {
+        ExtBuilder::build().execute_with(|| {
+            PURGE_STALE_ENCLAVES.with(|v| *v.borrow_mut() = true);
+            let (signer1, signer_attn1) = get_signer1();
+            let (signer2, signer_attn2) = get_signer2();
+
+            assert_ok!(Registry::register_enclave(
+                Origin::signed(signer1.clone()),
+                TEST1_CERT.to_vec(),
+                signer_attn1,
+                URL.to_vec(),
+                false
+            ));
+            // enclave 1 is a week older than enclave 2
+            Timestamp::set_timestamp(TEST2_TIMESTAMP as u64 * 1000);
+            assert_ok!(Registry::register_enclave(
+                Origin::signed(signer2.clone()),
+                TEST2_CERT.to_vec(),
+                signer_attn2,
+                URL.to_vec(),
+                false
+            ));
+            assert_eq!(Registry::enclave_count(), 1);
+            assert_eq!(Registry::enclave(1).pubkey, signer2);
+            assert!(!<EnclaveIndex<TestRuntime>>::exists(&signer1));
+            let expected_event = TestEvent::generic_event(RawEvent::RemovedEnclave(signer1));
+            assert!(System::events().iter().any(|a| a.event == expected_event));
+        })
+    }
+
+    #[test]
+    fn purge_scans_at_most_max_purge_scan_enclaves() {
+        ExtBuilder::build().execute_with(|| {
+            // enclave 3 has the oldest attestation, but is beyond the first scan
+            let signers: Vec<AccountId> = [TEST2_TIMESTAMP, TEST3_TIMESTAMP, TEST1_TIMESTAMP]
+                .iter()
+                .enumerate()
+                .map(|(i, timestamp)| {
+                    let pair = sr25519::Pair::from_seed(&[i as u8 + 1; 32]);
+                    let signer: AccountId = AccountPublic::from(pair.public()).into_account();
+                    let report = SgxReport {
+                        mr_enclave: TEST1_MRENCLAVE,
+                        pubkey: pair.public().0,
+                        status: SgxStatus::Ok,
+                        timestamp: *timestamp,
+                    };
+                    assert_ok!(Registry::register_verified_enclave(&signer, &report, URL.to_vec()));
+                    signer
+                })
+                .collect();
+            let (outsider, _) = get_signer1();
+            assert_eq!(MaxPurgeScan::get(), 2);
+            Timestamp::set_timestamp((TEST3_TIMESTAMP as u64 + 2 * 86400) * 1000);
+
+            assert_ok!(Registry::purge_stale_enclave(&outsider));
+            assert_eq!(Registry::enclave_count(), 2);
+            assert!(!<EnclaveIndex<TestRuntime>>::exists(&signers[0]));
+            assert_eq!(Registry::purge_cursor(), 2);
+
+            // the next scan continues where the last one stopped
+            assert_ok!(Registry::purge_stale_enclave(&outsider));
+            assert_eq!(Registry::enclave_count(), 1);
+            assert!(!<EnclaveIndex<TestRuntime>>::exists(&signers[2]));
+        })
+    }
+
+    #[test]
+    fn registering_keeps_stale_enclave_if_disabled() {
+        ExtBuilder::build().execute_with(|| {
+            let (signer1, signer_attn1) = get_signer1();
+            let (signer2, signer_attn2) = get_signer2();
+
+            assert_ok!(Registry::register_enclave(
+                Origin::signed(signer1),
+                TEST1_CERT.to_vec(),
+                signer_attn1,
+                URL.to_vec(),
+                false
+            ));
+            Timestamp::set_timestamp(TEST2_TIMESTAMP as u64 * 1000);
+            assert_ok!(Registry::register_enclave(
+                Origin::signed(signer2),
+                TEST2_CERT.to_vec(),
+                signer_attn2,
+                URL.to_vec(),
+                false
+            ));
+            assert_eq!(Registry::enclave_count(), 2);
+        })
+    }
+
+    #[test]
+    fn unregister_stale_enclave_works() {
+        ExtBuilder::build().execute_with(|| {
+            let (signer1, signer_attn1) = get_signer1();
+            let (signer2, _) = get_signer2();
+
+            assert_ok!(Registry::register_enclave(
+                Origin::signed(signer1.clone()),
+                TEST1_CERT.to_vec(),
+                signer_attn1,
+                URL.to_vec(),
+                false
+            ));
+            Timestamp::set_timestamp(TEST2_TIMESTAMP as u64 * 1000);
+            assert_ok!(Registry::unregister_stale_enclave(Origin::signed(signer2), 1));
+            assert_eq!(Registry::enclave_count(), 0);
+            assert!(!<EnclaveIndex<TestRuntime>>::exists(&signer1));
+            let expected_event = TestEvent::generic_event(RawEvent::RemovedEnclave(signer1));
+            assert!(System::events().iter().any(|a| a.event == expected_event));
+        })
+    }
+
+    #[test]
+    fn unregister_fresh_enclave_by_others_fails() {
+        ExtBuilder::build().execute_with(|| {
+            let (signer1, signer_attn1) = get_signer1();
+            let (signer2, _) = get_signer2();
+
+            assert_ok!(Registry::register_enclave(
+                Origin::signed(signer1),
+                TEST1_CERT.to_vec(),
+                signer_attn1,
+                URL.to_vec(),
+                false
+            ));
+            Timestamp::set_timestamp(TEST1_TIMESTAMP as u64 * 1000);
+            assert_eq!(
+                Registry::unregister_stale_enclave(Origin::signed(signer2.clone()), 1),
+                Err(Error::EnclaveNotStale.into())
+            );
+            assert!(Registry::unregister_stale_enclave(Origin::signed(signer2), 2).is_err());
+            assert_eq!(Registry::enclave_count(), 1);
+        })
+    }
+
     #[test]
     fn call_worker_works() {
         ExtBuilder::build().execute_with(|| {
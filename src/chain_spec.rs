@@ -8,7 +8,7 @@ use sr_primitives::traits::{IdentifyAccount, Verify};
 use substrate_service;
 use substratee_node_runtime::{
     AccountId, AuraConfig, BalancesConfig, GenesisConfig, GrandpaConfig, IndicesConfig, Signature,
-    SudoConfig, SystemConfig, WASM_BINARY,
+    SubstraTEERegistryConfig, SudoConfig, SystemConfig, WASM_BINARY,
 };
 
 // Note this is the URL for the telemetry server
@@ -153,5 +153,6 @@ fn testnet_genesis(
                 .map(|x| (x.1.clone(), 1))
                 .collect(),
         }),
+        substratee_registry: Some(SubstraTEERegistryConfig { enclaves: vec![] }),
     }
 }
@@ -0,0 +1,67 @@
+//! SubstraTEE specific RPC methods.
+
+use std::sync::Arc;
+
+use codec::Encode;
+use jsonrpc_core::{Error, ErrorCode, Result};
+use jsonrpc_derive::rpc;
+use primitives::Bytes;
+use sr_api::ProvideRuntimeApi;
+use sr_primitives::{generic::BlockId, traits::Block as BlockT};
+use substrate_client::blockchain::HeaderBackend;
+use substratee_node_runtime::{opaque::Block, substratee_registry::SubstraTEERegistryApi, AccountId};
+
+/// The RPC extension type the node's services are built with.
+pub type RpcExtension = jsonrpc_core::IoHandler<substrate_rpc::Metadata>;
+
+/// SubstraTEE RPC methods.
+#[rpc]
+pub trait SubstraTEEApi<BlockHash> {
+    /// Returns whether this node's attestation verifier accepts a known-good attestation.
+    #[rpc(name = "system_attestationHealth")]
+    fn attestation_health(&self) -> Result<bool>;
+
+    /// Returns all registered enclaves with their index as of block `at` (the best block
+    /// if omitted), SCALE encoded as `Vec<(u64, Enclave<AccountId, Vec<u8>>)>`.
+    #[rpc(name = "substratee_getEnclaves")]
+    fn enclaves(&self, at: Option<BlockHash>) -> Result<Bytes>;
+}
+
+/// Implementation of the SubstraTEE RPC methods.
+pub struct SubstraTEE<C> {
+    client: Arc<C>,
+}
+
+impl<C> SubstraTEEApi<<Block as BlockT>::Hash> for SubstraTEE<C>
+where
+    C: ProvideRuntimeApi + HeaderBackend<Block> + Send + Sync + 'static,
+    C::Api: SubstraTEERegistryApi<Block, AccountId>,
+{
+    fn attestation_health(&self) -> Result<bool> {
+        Ok(host_calls::verify_known_good_attestation())
+    }
+
+    fn enclaves(&self, at: Option<<Block as BlockT>::Hash>) -> Result<Bytes> {
+        let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+        self.client
+            .runtime_api()
+            .enclaves(&at)
+            .map(|enclaves| enclaves.encode().into())
+            .map_err(|e| Error {
+                code: ErrorCode::ServerError(1),
+                message: "Unable to query enclaves.".into(),
+                data: Some(format!("{:?}", e).into()),
+            })
+    }
+}
+
+/// Instantiates all SubstraTEE RPC extensions.
+pub fn create<C>(client: Arc<C>) -> RpcExtension
+where
+    C: ProvideRuntimeApi + HeaderBackend<Block> + Send + Sync + 'static,
+    C::Api: SubstraTEERegistryApi<Block, AccountId>,
+{
+    let mut io = jsonrpc_core::IoHandler::default();
+    io.extend_with(SubstraTEEApi::to_delegate(SubstraTEE { client }));
+    io
+}
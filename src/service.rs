@@ -81,6 +81,9 @@ macro_rules! new_full_start {
             import_setup = Some((grandpa_block_import, grandpa_link));
 
             Ok(import_queue)
+        })?
+        .with_rpc_extensions(|client, _pool, _backend, _fetcher, _remote_blockchain| {
+            Ok(crate::rpc::create(client))
         })?;
 
         (builder, import_setup, inherent_data_providers)
@@ -242,6 +245,9 @@ pub fn new_light<C: Send + Default + 'static>(
                 Ok((import_queue, finality_proof_request_builder))
             },
         )?
+        .with_rpc_extensions(|client, _pool, _backend, _fetcher, _remote_blockchain| {
+            Ok(crate::rpc::create(client))
+        })?
         .with_network_protocol(|_| Ok(NodeProtocol::new()))?
         .with_finality_proof_provider(|client, backend| {
             Ok(Arc::new(GrandpaFinalityProofProvider::new(backend, client)) as _)